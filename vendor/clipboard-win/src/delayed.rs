@@ -0,0 +1,264 @@
+//!Delayed (on-demand) clipboard rendering.
+//!
+//!Lets a format be registered on clipboard without providing its data up front; the data is
+//!produced lazily, only once another process actually asks for it. This avoids eagerly
+//!allocating large payloads (big images, HTML) on every copy.
+//!
+//!Implemented with a hidden message-only window, running on a dedicated thread, whose window
+//!procedure handles `WM_RENDERFORMAT` and `WM_RENDERALLFORMATS`.
+
+use std::io;
+use std::ptr;
+use std::thread;
+use std::sync::mpsc;
+
+use winapi::minwindef::{UINT, WPARAM, LPARAM, LRESULT};
+use winapi::windef::HWND;
+use winapi::winuser::{
+    CREATESTRUCTW,
+    WNDCLASSW,
+    MSG,
+    CS_OWNDC,
+    CW_USEDEFAULT,
+    GWLP_USERDATA,
+    HWND_MESSAGE,
+    WM_CREATE,
+    WM_DESTROY,
+    WM_RENDERFORMAT,
+    WM_RENDERALLFORMATS,
+    WM_USER
+};
+
+use user32::{
+    CreateWindowExW,
+    DefWindowProcW,
+    DestroyWindow,
+    RegisterClassW,
+    UnregisterClassW,
+    GetMessageW,
+    TranslateMessage,
+    DispatchMessageW,
+    PostMessageW,
+    SetWindowLongPtrW,
+    GetWindowLongPtrW
+};
+use kernel32::GetModuleHandleW;
+
+use ::raw;
+use ::utils;
+
+const WINDOW_CLASS: &'static [u16] = &[
+    'c' as u16, 'l' as u16, 'i' as u16, 'p' as u16, 'b' as u16, 'o' as u16, 'a' as u16, 'r' as u16,
+    'd' as u16, '-' as u16, 'w' as u16, 'i' as u16, 'n' as u16, ':' as u16, ':' as u16,
+    'D' as u16, 'e' as u16, 'l' as u16, 'a' as u16, 'y' as u16, 'e' as u16, 'd' as u16, 0
+];
+const WM_SHUTDOWN: UINT = WM_USER + 1;
+
+///Callback producing data for a delayed-render format, invoked with the requested format code.
+pub type RenderFn = Box<FnMut(u32) -> io::Result<Vec<u8>> + Send>;
+
+struct WindowState {
+    render: RenderFn,
+}
+
+///Handle to a thread owning one or more clipboard formats that are rendered on demand.
+///
+///Registers `formats` with a `NULL` data handle so their payloads are produced only when
+///requested by another process. Unregisters the window and joins the rendering thread on drop.
+pub struct DelayedOwner {
+    window: HWND,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+unsafe impl Send for DelayedOwner {}
+
+impl DelayedOwner {
+    ///Creates a new delayed owner, registering `formats` on clipboard and rendering each of
+    ///them via `render` whenever another process requests it.
+    ///
+    ///`WM_RENDERFORMAT`/`WM_RENDERALLFORMATS` are only ever delivered to the window that was
+    ///the registered clipboard owner (`hWndNewOwner` passed to `OpenClipboard`) at the moment
+    ///a `NULL`-data format was set on it. So the message-only window is created first, clipboard
+    ///is then opened with that window as owner via [raw::open_for](../raw/fn.open_for.html),
+    ///and only then is each format registered with [raw::set_delayed](../raw/fn.set_delayed.html).
+    pub fn new(formats: &[u32], render: RenderFn) -> io::Result<DelayedOwner> {
+        let (tx, rx) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            match create_window(render) {
+                Ok(window) => {
+                    let _ = tx.send(Ok(window));
+                    run_message_loop();
+                    unregister_window(window);
+                },
+                Err(error) => {
+                    let _ = tx.send(Err(error));
+                }
+            }
+        });
+
+        let window = match rx.recv() {
+            Ok(Ok(window)) => window,
+            Ok(Err(error)) => return Err(error),
+            Err(_) => return Err(io::Error::new(io::ErrorKind::Other, "Delayed render thread failed to start")),
+        };
+
+        //Built now, rather than after registering formats below, so that a failure there still
+        //drops `owner` before returning: Drop posts WM_SHUTDOWN and joins `thread`, instead of
+        //leaking the already-running message loop (and its window/class) blocked in GetMessageW.
+        let owner = DelayedOwner { window, thread: Some(thread) };
+
+        register_formats(window, formats)?;
+
+        Ok(owner)
+    }
+}
+
+fn register_formats(window: HWND, formats: &[u32]) -> io::Result<()> {
+    raw::open_for(window)?;
+
+    for &format in formats {
+        if let Err(error) = raw::set_delayed(format) {
+            let _ = raw::close();
+            return Err(error);
+        }
+    }
+
+    raw::close()
+}
+
+impl Drop for DelayedOwner {
+    fn drop(&mut self) {
+        unsafe {
+            PostMessageW(self.window, WM_SHUTDOWN, 0, 0);
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn create_window(render: RenderFn) -> io::Result<HWND> {
+    unsafe {
+        let instance = GetModuleHandleW(ptr::null());
+
+        let class = WNDCLASSW {
+            style: CS_OWNDC,
+            lpfnWndProc: Some(window_proc),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hInstance: instance,
+            hIcon: ptr::null_mut(),
+            hCursor: ptr::null_mut(),
+            hbrBackground: ptr::null_mut(),
+            lpszMenuName: ptr::null(),
+            lpszClassName: WINDOW_CLASS.as_ptr(),
+        };
+
+        //Re-registering is harmless: RegisterClassW simply fails with ERROR_CLASS_ALREADY_EXISTS
+        //for the second and further DelayedOwner on the same process, which we ignore.
+        RegisterClassW(&class);
+
+        let state = Box::into_raw(Box::new(WindowState { render }));
+
+        let window = CreateWindowExW(
+            0,
+            WINDOW_CLASS.as_ptr(),
+            ptr::null(),
+            0,
+            CW_USEDEFAULT, CW_USEDEFAULT, CW_USEDEFAULT, CW_USEDEFAULT,
+            HWND_MESSAGE,
+            ptr::null_mut(),
+            instance,
+            state as *mut _,
+        );
+
+        if window.is_null() {
+            drop(Box::from_raw(state));
+            return Err(utils::get_last_error());
+        }
+
+        Ok(window)
+    }
+}
+
+fn unregister_window(window: HWND) {
+    unsafe {
+        DestroyWindow(window);
+        UnregisterClassW(WINDOW_CLASS.as_ptr(), GetModuleHandleW(ptr::null()));
+    }
+}
+
+fn run_message_loop() {
+    let mut msg: MSG = unsafe { std::mem::zeroed() };
+
+    loop {
+        let result = unsafe { GetMessageW(&mut msg, ptr::null_mut(), 0, 0) };
+
+        if result <= 0 {
+            break;
+        }
+
+        if msg.message == WM_SHUTDOWN {
+            break;
+        }
+
+        unsafe {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+}
+
+fn render(state: &mut WindowState, format: u32) {
+    if let Ok(data) = (state.render)(format) {
+        let _ = raw::set_now(format, &data);
+    }
+}
+
+unsafe extern "system" fn window_proc(window: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_CREATE => {
+            let create_struct = lparam as *const CREATESTRUCTW;
+            SetWindowLongPtrW(window, GWLP_USERDATA, (*create_struct).lpCreateParams as isize);
+            0
+        },
+        WM_RENDERFORMAT => {
+            let state = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut WindowState;
+
+            if let Some(state) = state.as_mut() {
+                render(state, wparam as u32);
+            }
+
+            0
+        },
+        WM_RENDERALLFORMATS => {
+            let state = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut WindowState;
+
+            if let Some(state) = state.as_mut() {
+                //Unlike WM_RENDERFORMAT, clipboard isn't already open here; re-open with this
+                //window as owner so it remains the one any still-unrendered formats are bound to.
+                if raw::open_for(window).is_ok() {
+                    for format in raw::EnumFormats::new() {
+                        render(state, format);
+                    }
+
+                    let _ = raw::close();
+                }
+            }
+
+            0
+        },
+        WM_DESTROY => {
+            let state = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut WindowState;
+
+            if !state.is_null() {
+                drop(Box::from_raw(state));
+            }
+
+            0
+        },
+        _ => DefWindowProcW(window, msg, wparam, lparam),
+    }
+}
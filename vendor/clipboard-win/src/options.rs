@@ -0,0 +1,39 @@
+//!Clipboard clearing strategies, used to control whether [set_with](../raw/fn.set_with.html)
+//!wipes existing clipboard contents before writing.
+
+use std::io;
+
+use ::raw;
+
+///Describes whether clipboard should be emptied before a format is written onto it.
+pub trait Clearing {
+    ///Function called right before ```SetClipboardData```.
+    const EMPTY_FN: fn() -> io::Result<()>;
+}
+
+///Empties clipboard before writing data.
+///
+///This is the behaviour of the plain [set](../raw/fn.set.html) function and is correct
+///whenever only a single format is going to be placed on clipboard.
+pub struct Clear;
+
+impl Clearing for Clear {
+    const EMPTY_FN: fn() -> io::Result<()> = raw::empty;
+}
+
+///Leaves clipboard contents untouched.
+///
+///Use this to stack several formats (e.g. ```CF_UNICODETEXT``` and a custom format) within a
+///single open/close session: call [set_with](../raw/fn.set_with.html)```::<NoClear>``` for every
+///format but the first, which should still clear via [Clear](struct.Clear.html).
+pub struct NoClear;
+
+impl NoClear {
+    fn noop() -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Clearing for NoClear {
+    const EMPTY_FN: fn() -> io::Result<()> = NoClear::noop;
+}
@@ -12,7 +12,9 @@
 
 use ::std;
 use std::cmp;
-use std::os::windows::ffi::OsStrExt;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
 use std::os::raw::{
     c_int,
     c_uint
@@ -22,17 +24,23 @@ use std::io;
 
 use ::utils;
 use ::formats;
+use ::options::{Clearing, Clear};
 
 use winapi::basetsd::{
     SIZE_T
 };
+use winapi::shellapi::HDROP;
+use winapi::windef::HWND;
+use winapi::wingdi::BITMAPINFOHEADER;
+use winapi::winnls::{CP_ACP, CP_OEMCP};
 
 use kernel32::{
     GlobalSize,
     GlobalLock,
     GlobalUnlock,
     GlobalAlloc,
-    GlobalFree
+    GlobalFree,
+    MultiByteToWideChar
 };
 
 use user32::{
@@ -49,6 +57,8 @@ use user32::{
     SetClipboardData
 };
 
+use shell32::DragQueryFileW;
+
 #[inline]
 ///Opens clipboard.
 ///
@@ -62,8 +72,31 @@ use user32::{
 ///
 ///* Clipboard can be accessed for read and write operations.
 pub fn open() -> io::Result<()> {
+    open_for(ptr::null_mut())
+}
+
+#[inline]
+///Opens clipboard, making `owner` the registered clipboard owner.
+///
+///Wrapper around ```OpenClipboard``` called with `owner` as window handle; [open](fn.open.html)
+///calls it with a null handle.
+///
+///This matters for delayed rendering: `WM_RENDERFORMAT`/`WM_RENDERALLFORMATS` are delivered to
+///whichever window was passed as `hWndNewOwner` here at the time a `NULL`-data format was
+///registered with [set_delayed](fn.set_delayed.html), so that window must already exist and
+///must be passed to `open_for` *before* `set_delayed` is called. See
+///[delayed::DelayedOwner](../delayed/struct.DelayedOwner.html).
+///
+///# Pre-conditions:
+///
+///* Clipboard is not opened yet.
+///
+///# Post-conditions:
+///
+///* Clipboard can be accessed for read and write operations.
+pub fn open_for(owner: HWND) -> io::Result<()> {
     unsafe {
-        if OpenClipboard(ptr::null_mut()) == 0 {
+        if OpenClipboard(owner) == 0 {
             return Err(utils::get_last_error());
         }
     }
@@ -236,14 +269,265 @@ pub fn get_string() -> io::Result<String> {
     }
 }
 
+///Retrieves text from clipboard, accepting `CF_UNICODETEXT`, `CF_TEXT` or `CF_OEMTEXT`.
+///
+///Specialized version of [get_string](fn.get_string.html) that additionally falls back to
+///converting ANSI/OEM code-page bytes via ```MultiByteToWideChar``` when no `CF_UNICODETEXT`
+///is available, for legacy applications which only ever publish ```CF_TEXT```.
+///
+///# Pre-conditions:
+///
+///* [open()](fn.open.html) has been called.
+pub fn get_string_any() -> io::Result<String> {
+    if is_format_avail(formats::CF_UNICODETEXT) {
+        return get_string();
+    }
+
+    let (format, code_page) = if is_format_avail(formats::CF_TEXT) {
+        (formats::CF_TEXT, CP_ACP)
+    }
+    else if is_format_avail(formats::CF_OEMTEXT) {
+        (formats::CF_OEMTEXT, CP_OEMCP)
+    }
+    else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "No text format is available on clipboard"));
+    };
+
+    let clipboard_data = unsafe { GetClipboardData(format) };
+
+    if clipboard_data.is_null() {
+        return Err(utils::get_last_error());
+    }
+
+    unsafe {
+        let data_ptr = GlobalLock(clipboard_data) as *const i8;
+
+        if data_ptr.is_null() {
+            return Err(utils::get_last_error());
+        }
+
+        let data_size = GlobalSize(clipboard_data) as c_int;
+
+        //First call with a null output buffer returns the required size, in characters.
+        let buff_size = MultiByteToWideChar(code_page, 0, data_ptr, data_size, ptr::null_mut(), 0);
+        let mut buff: Vec<u16> = vec![0; buff_size as usize];
+        MultiByteToWideChar(code_page, 0, data_ptr, data_size, buff.as_mut_ptr(), buff_size);
+
+        GlobalUnlock(clipboard_data);
+
+        let mut result = String::from_utf16_lossy(&buff);
+
+        //As with get_string, trim the trailing null character if present.
+        if let Some(last) = result.pop() {
+            if last != '\0' {
+                result.push(last);
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+///Sets string onto clipboard as `CF_UNICODETEXT`.
+///
+///# Pre-conditions:
+///
+///* [open()](fn.open.html) has been called.
+pub fn set_string(data: &str) -> io::Result<()> {
+    let mut utf16_buff: Vec<u16> = data.encode_utf16().collect();
+    utf16_buff.push(0);
+
+    let bytes = unsafe {
+        std::slice::from_raw_parts(utf16_buff.as_ptr() as *const u8, utf16_buff.len() * std::mem::size_of::<u16>())
+    };
+
+    set(formats::CF_UNICODETEXT, bytes)
+}
+
+///Retrieves list of file paths from `CF_HDROP` format.
+///
+///Wrapper around ```DragQueryFileW```.
+///
+///# Pre-conditions:
+///
+///* [open()](fn.open.html) has been called.
+pub fn get_file_list() -> io::Result<Vec<PathBuf>> {
+    let clipboard_data = unsafe { GetClipboardData(formats::CF_HDROP) };
+
+    if clipboard_data.is_null() {
+        Err(utils::get_last_error())
+    }
+    else {
+        unsafe {
+            let hdrop = GlobalLock(clipboard_data) as HDROP;
+
+            if hdrop.is_null() {
+                return Err(utils::get_last_error());
+            }
+
+            let num_files = DragQueryFileW(hdrop, 0xFFFFFFFF, ptr::null_mut(), 0);
+            let mut result = Vec::with_capacity(num_files as usize);
+
+            for idx in 0..num_files {
+                //First call with a null buffer returns the required length, excluding the null terminator.
+                let len = DragQueryFileW(hdrop, idx, ptr::null_mut(), 0);
+                let mut path_buff: Vec<u16> = vec![0; len as usize + 1];
+
+                DragQueryFileW(hdrop, idx, path_buff.as_mut_ptr(), path_buff.len() as c_uint);
+                path_buff.pop(); //drop null terminator
+
+                result.push(PathBuf::from(OsString::from_wide(&path_buff)));
+            }
+
+            GlobalUnlock(clipboard_data);
+
+            Ok(result)
+        }
+    }
+}
+
+//`winapi` 0.2 doesn't define `BITMAPFILEHEADER` (it's specific to the BMP *file* format, not
+//a structure the clipboard or GDI ever hand you), and a naive `#[repr(C)]` version of it would
+//pick up padding after `bfType` that breaks its mandated 14-byte wire size. So its 14 bytes are
+//written out field-by-field below instead of being transmuted from a Rust struct.
+const BITMAP_FILE_HEADER_SIZE: usize = 14;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+fn push_u16_le(buff: &mut Vec<u8>, value: u16) {
+    buff.push((value & 0xff) as u8);
+    buff.push((value >> 8) as u8);
+}
+
+fn push_u32_le(buff: &mut Vec<u8>, value: u32) {
+    buff.push((value & 0xff) as u8);
+    buff.push(((value >> 8) & 0xff) as u8);
+    buff.push(((value >> 16) & 0xff) as u8);
+    buff.push(((value >> 24) & 0xff) as u8);
+}
+
+///Retrieves bitmap image as bytes of a `.bmp` file from `CF_DIB` format.
+///
+///`CF_DIB` holds a ```BITMAPINFOHEADER``` followed by optional colour masks/palette and the
+///pixel bits, so a ```BITMAPFILEHEADER``` is synthesized and prepended to make the result a
+///valid, directly writable `.bmp` file.
+///
+///# Note:
+///
+///Only `BI_RGB`, `BI_BITFIELDS` and `BI_ALPHABITFIELDS` compression is supported. Other
+///compressions (e.g. `BI_RLE8`, `BI_JPEG`, `BI_PNG`) result in an error, rather than a `.bmp`
+///with an incorrect `bfOffBits`.
+///
+///# Pre-conditions:
+///
+///* [open()](fn.open.html) has been called.
+pub fn get_bitmap() -> io::Result<Vec<u8>> {
+    let clipboard_data = unsafe { GetClipboardData(formats::CF_DIB) };
+
+    if clipboard_data.is_null() {
+        Err(utils::get_last_error())
+    }
+    else {
+        unsafe {
+            let data_ptr = GlobalLock(clipboard_data) as *const u8;
+
+            if data_ptr.is_null() {
+                return Err(utils::get_last_error());
+            }
+
+            let data_size = GlobalSize(clipboard_data) as usize;
+
+            if data_size < std::mem::size_of::<BITMAPINFOHEADER>() {
+                GlobalUnlock(clipboard_data);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "CF_DIB data is smaller than BITMAPINFOHEADER"));
+            }
+
+            let info_header = &*(data_ptr as *const BITMAPINFOHEADER);
+
+            //BI_BITFIELDS/BI_ALPHABITFIELDS prepend 3/4 DWORD colour masks before the pixel bits.
+            let bitfields_size = match info_header.biCompression {
+                BI_RGB => 0,
+                BI_BITFIELDS => 3 * std::mem::size_of::<u32>(),
+                BI_ALPHABITFIELDS => 4 * std::mem::size_of::<u32>(),
+                _ => {
+                    GlobalUnlock(clipboard_data);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Unsupported CF_DIB compression"));
+                }
+            };
+
+            let palette_size = if info_header.biClrUsed != 0 {
+                info_header.biClrUsed as usize
+            }
+            else if info_header.biBitCount <= 8 {
+                1usize << info_header.biBitCount
+            }
+            else {
+                0
+            } * std::mem::size_of::<u32>();
+
+            let off_bits = BITMAP_FILE_HEADER_SIZE + info_header.biSize as usize + bitfields_size + palette_size;
+
+            let mut result = Vec::with_capacity(BITMAP_FILE_HEADER_SIZE + data_size);
+
+            push_u16_le(&mut result, 0x4d42); //"BM"
+            push_u32_le(&mut result, (BITMAP_FILE_HEADER_SIZE + data_size) as u32); //bfSize
+            push_u16_le(&mut result, 0); //bfReserved1
+            push_u16_le(&mut result, 0); //bfReserved2
+            push_u32_le(&mut result, off_bits as u32); //bfOffBits
+
+            result.extend_from_slice(std::slice::from_raw_parts(data_ptr, data_size));
+
+            GlobalUnlock(clipboard_data);
+
+            Ok(result)
+        }
+    }
+}
+
+///Sets `CF_DIB` data onto clipboard from the bytes of a `.bmp` file.
+///
+///Strips the leading 14-byte ```BITMAPFILEHEADER``` and places the remaining
+///```BITMAPINFOHEADER``` plus pixel bits onto clipboard as `CF_DIB`.
+///
+///# Pre-conditions:
+///
+///* [open()](fn.open.html) has been called.
+pub fn set_bitmap(data: &[u8]) -> io::Result<()> {
+    if data.len() <= BITMAP_FILE_HEADER_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough data for BITMAPFILEHEADER"));
+    }
+
+    set(formats::CF_DIB, &data[BITMAP_FILE_HEADER_SIZE..])
+}
+
 ///Sets data onto clipboard with specified format.
 ///
 ///Wrapper around ```SetClipboardData```.
 ///
+///Empties clipboard before writing, same as ```set_with::<Clear>```. To place several formats
+///onto clipboard within a single open/close session, use [set_with](fn.set_with.html) instead.
+///
 ///# Pre-conditions:
 ///
 ///* [open()](fn.open.html) has been called.
 pub fn set(format: u32, data: &[u8]) -> io::Result<()> {
+    set_with::<Clear>(format, data)
+}
+
+///Sets data onto clipboard with specified format, controlling whether clipboard is emptied first.
+///
+///Wrapper around ```SetClipboardData```.
+///
+///Use [options::NoClear](../options/struct.NoClear.html) to stack multiple formats within a
+///single open/close session; each call with [options::Clear](../options/struct.Clear.html)
+///would otherwise wipe out formats set earlier in the same session.
+///
+///# Pre-conditions:
+///
+///* [open()](fn.open.html) has been called.
+pub fn set_with<C: Clearing>(format: u32, data: &[u8]) -> io::Result<()> {
     const GHND: c_uint = 0x42;
     let size = data.len();
 
@@ -258,7 +542,11 @@ pub fn set(format: u32, data: &[u8]) -> io::Result<()> {
 
             ptr::copy_nonoverlapping(data.as_ptr(), lock, size);
             GlobalUnlock(alloc_handle);
-            EmptyClipboard();
+
+            if let Err(error) = C::EMPTY_FN() {
+                GlobalFree(alloc_handle);
+                return Err(error);
+            }
 
             if SetClipboardData(format, alloc_handle).is_null() {
                 let result = utils::get_last_error();
@@ -272,6 +560,54 @@ pub fn set(format: u32, data: &[u8]) -> io::Result<()> {
     }
 }
 
+///Sets data for a format whose rendering was requested by another process.
+///
+///Unlike [set](fn.set.html), it does not empty the clipboard: used while handling `WM_RENDERFORMAT`/`WM_RENDERALLFORMATS`, where the clipboard
+///is already open and owned by the caller. See [delayed::DelayedOwner](../delayed/struct.DelayedOwner.html).
+pub(crate) fn set_now(format: u32, data: &[u8]) -> io::Result<()> {
+    const GHND: c_uint = 0x42;
+    let size = data.len();
+
+    let alloc_handle = unsafe { GlobalAlloc(GHND, size as SIZE_T) };
+
+    if alloc_handle.is_null() {
+        return Err(utils::get_last_error());
+    }
+
+    unsafe {
+        let lock = GlobalLock(alloc_handle) as *mut u8;
+
+        ptr::copy_nonoverlapping(data.as_ptr(), lock, size);
+        GlobalUnlock(alloc_handle);
+
+        if SetClipboardData(format, alloc_handle).is_null() {
+            let result = utils::get_last_error();
+            GlobalFree(alloc_handle);
+            Err(result)
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
+///Registers `format` on clipboard without providing its data, so it can be rendered lazily.
+///
+///Wrapper around ```SetClipboardData``` called with a `NULL` data handle. Pair with a window
+///handling `WM_RENDERFORMAT`/`WM_RENDERALLFORMATS`, see [delayed::DelayedOwner](../delayed/struct.DelayedOwner.html).
+///
+///# Pre-conditions:
+///
+///* [open_for()](fn.open_for.html) has been called with the window that handles rendering.
+pub fn set_delayed(format: u32) -> io::Result<()> {
+    if unsafe { SetClipboardData(format, ptr::null_mut()) }.is_null() {
+        Err(utils::get_last_error())
+    }
+    else {
+        Ok(())
+    }
+}
+
 #[inline(always)]
 ///Determines whenever provided clipboard format is available on clipboard or not.
 pub fn is_format_avail(format: u32) -> bool {
@@ -430,3 +766,76 @@ pub fn register_format<T: ?Sized + AsRef<std::ffi::OsStr>>(name: &T) -> io::Resu
         Ok(result)
     }
 }
+
+const HTML_START_FRAGMENT: &'static str = "<!--StartFragment-->";
+const HTML_END_FRAGMENT: &'static str = "<!--EndFragment-->";
+
+fn html_header(start_html: usize, end_html: usize, start_fragment: usize, end_fragment: usize) -> String {
+    format!("Version:0.9\r\nStartHTML:{:0>10}\r\nEndHTML:{:0>10}\r\nStartFragment:{:0>10}\r\nEndFragment:{:0>10}\r\n",
+            start_html, end_html, start_fragment, end_fragment)
+}
+
+///Sets HTML fragment onto clipboard via the registered ```HTML Format```.
+///
+///Wraps `fragment` with the `CF_HTML` descriptor header expected by browsers and Office.
+///The header's offsets are computed after its own length is known, since they count bytes
+///from the start of the whole buffer, header included.
+///
+///# Pre-conditions:
+///
+///* [open()](fn.open.html) has been called.
+pub fn set_html(fragment: &str) -> io::Result<()> {
+    let format = register_format("HTML Format")?;
+
+    //Offsets are zero-padded to 10 digits so the header's length is fixed; compute it with
+    //placeholder zeros first, then back-fill the real offsets.
+    let start_html = html_header(0, 0, 0, 0).len();
+    let start_fragment = start_html + HTML_START_FRAGMENT.len();
+    let end_fragment = start_fragment + fragment.len();
+    let end_html = end_fragment + HTML_END_FRAGMENT.len();
+
+    let mut result = html_header(start_html, end_html, start_fragment, end_fragment);
+    result.push_str(HTML_START_FRAGMENT);
+    result.push_str(fragment);
+    result.push_str(HTML_END_FRAGMENT);
+
+    set(format, result.as_bytes())
+}
+
+///Retrieves HTML fragment from clipboard via the registered ```HTML Format```.
+///
+///Locates `StartFragment`/`EndFragment` offsets in the `CF_HTML` descriptor header and slices
+///the fragment out of the raw clipboard bytes.
+///
+///# Pre-conditions:
+///
+///* [open()](fn.open.html) has been called.
+pub fn get_html() -> io::Result<String> {
+    let format = register_format("HTML Format")?;
+
+    let mut buffer = vec![0u8; size(format).unwrap_or(0)];
+    let read = get(format, &mut buffer)?;
+    buffer.truncate(read);
+
+    let text = String::from_utf8_lossy(&buffer);
+
+    let start_fragment = html_fragment_offset(&text, "StartFragment:")?;
+    let end_fragment = html_fragment_offset(&text, "EndFragment:")?;
+
+    if start_fragment > end_fragment || end_fragment > buffer.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Malformed CF_HTML descriptor"));
+    }
+
+    Ok(String::from_utf8_lossy(&buffer[start_fragment..end_fragment]).into_owned())
+}
+
+fn html_fragment_offset(descriptor: &str, marker: &str) -> io::Result<usize> {
+    let pos = descriptor.find(marker)
+                         .map(|pos| pos + marker.len())
+                         .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed CF_HTML descriptor"))?;
+
+    descriptor[pos..].split_whitespace()
+                     .next()
+                     .and_then(|num| num.parse::<usize>().ok())
+                     .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed CF_HTML descriptor"))
+}
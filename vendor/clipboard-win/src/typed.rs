@@ -0,0 +1,114 @@
+//!Typed wrappers over the [raw](../raw/index.html) format functions.
+//!
+//!Each zero-sized format struct implements [Getter](trait.Getter.html) and/or
+//![Setter](trait.Setter.html), centralizing the choice of raw function and the
+//!open/lock/unlock lifecycle per format: every call opens the clipboard, performs the
+//!read/write (which itself handles the `GlobalLock`/`GlobalUnlock` pair), then closes it.
+
+use std::io;
+use std::path::PathBuf;
+
+use ::raw;
+
+fn with_clipboard<T, F: FnOnce() -> io::Result<T>>(action: F) -> io::Result<T> {
+    raw::open()?;
+
+    let result = action();
+    let close_result = raw::close();
+
+    result.and_then(|value| close_result.map(|_| value))
+}
+
+///Reads clipboard data of a specific format into `Out`.
+pub trait Getter<Out> {
+    ///Opens clipboard, reads its content into `out` and closes it again.
+    ///
+    ///Returns number of bytes/elements read.
+    fn read_clipboard(&self, out: &mut Out) -> io::Result<usize>;
+}
+
+///Writes clipboard data of a specific format from `In`.
+pub trait Setter<In: ?Sized> {
+    ///Opens clipboard, writes `data` onto it and closes it again.
+    fn write_clipboard(&self, data: &In) -> io::Result<()>;
+}
+
+///Arbitrary clipboard format, identified by its raw format code.
+pub struct RawData(pub u32);
+
+impl Getter<Vec<u8>> for RawData {
+    fn read_clipboard(&self, out: &mut Vec<u8>) -> io::Result<usize> {
+        with_clipboard(|| {
+            let size = raw::size(self.0).unwrap_or(0);
+            out.resize(size, 0);
+
+            let written = raw::get(self.0, out)?;
+            out.truncate(written);
+
+            Ok(written)
+        })
+    }
+}
+
+impl Setter<[u8]> for RawData {
+    fn write_clipboard(&self, data: &[u8]) -> io::Result<()> {
+        with_clipboard(|| raw::set(self.0, data))
+    }
+}
+
+///`CF_UNICODETEXT` format.
+pub struct Unicode;
+
+impl Getter<String> for Unicode {
+    fn read_clipboard(&self, out: &mut String) -> io::Result<usize> {
+        with_clipboard(|| {
+            let result = raw::get_string()?;
+            let len = result.len();
+            *out = result;
+
+            Ok(len)
+        })
+    }
+}
+
+impl Setter<str> for Unicode {
+    fn write_clipboard(&self, data: &str) -> io::Result<()> {
+        with_clipboard(|| raw::set_string(data))
+    }
+}
+
+///`CF_HDROP` format.
+pub struct FileList;
+
+impl Getter<Vec<PathBuf>> for FileList {
+    fn read_clipboard(&self, out: &mut Vec<PathBuf>) -> io::Result<usize> {
+        with_clipboard(|| {
+            let result = raw::get_file_list()?;
+            let len = result.len();
+            *out = result;
+
+            Ok(len)
+        })
+    }
+}
+
+///`CF_DIB`/`CF_BITMAP` format, read or written as a standalone `.bmp` byte stream.
+pub struct Bitmap;
+
+impl Getter<Vec<u8>> for Bitmap {
+    fn read_clipboard(&self, out: &mut Vec<u8>) -> io::Result<usize> {
+        with_clipboard(|| {
+            let result = raw::get_bitmap()?;
+            let len = result.len();
+            *out = result;
+
+            Ok(len)
+        })
+    }
+}
+
+impl Setter<[u8]> for Bitmap {
+    fn write_clipboard(&self, data: &[u8]) -> io::Result<()> {
+        with_clipboard(|| raw::set_bitmap(data))
+    }
+}